@@ -0,0 +1,371 @@
+use std::fmt;
+
+use anyhow::{Context, Result};
+use semver::{Version, VersionReq};
+
+use crate::workspace::Declaration;
+
+/// How a candidate version relates to whatever the author already wrote in
+/// `Cargo.toml` for this dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeClass {
+    /// Matches the existing requirement outright; nothing to renegotiate.
+    SatisfiesCurrent,
+    /// Outside the existing requirement but semver-compatible with it.
+    CompatibleBump,
+    /// A genuine breaking change relative to the existing requirement.
+    Breaking,
+}
+
+impl fmt::Display for ChangeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            ChangeClass::SatisfiesCurrent => "unchanged",
+            ChangeClass::CompatibleBump => "compatible",
+            ChangeClass::Breaking => "breaking",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub version: String,
+    pub class: ChangeClass,
+    pub downloads: u64,
+}
+
+/// A version as reported by the crates.io index, together with the adoption
+/// signal (`downloads`) that [`rank`] weighs alongside recency, and uses to
+/// break ties between equally-sized breaking changes.
+#[derive(Debug, Clone)]
+pub struct VersionInfo {
+    pub version: String,
+    pub downloads: u64,
+}
+
+/// Read the `VersionReq` currently written for `crate_name` at `declaration`,
+/// if any.
+pub fn read_requirement(declaration: &Declaration, crate_name: &str) -> Result<Option<VersionReq>> {
+    let Some(req_str) = crate::workspace::read_requirement_string(declaration, crate_name)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(VersionReq::parse(&req_str).with_context(|| {
+        format!("invalid version requirement for `{}`", crate_name)
+    })?))
+}
+
+/// Classify `version` relative to `req`'s first comparator, the way cargo's
+/// default caret requirements define compatibility.
+fn classify(req: Option<&VersionReq>, version: &Version) -> ChangeClass {
+    let Some(req) = req else {
+        return ChangeClass::Breaking;
+    };
+    if req.matches(version) {
+        return ChangeClass::SatisfiesCurrent;
+    }
+
+    let Some(baseline) = req.comparators.first() else {
+        return ChangeClass::Breaking;
+    };
+
+    let compatible = if baseline.major > 0 {
+        version.major == baseline.major
+    } else if let Some(minor) = baseline.minor {
+        if minor > 0 {
+            version.major == 0 && version.minor == minor
+        } else {
+            version.major == 0 && version.minor == 0
+        }
+    } else {
+        version.major == 0
+    };
+
+    if compatible {
+        ChangeClass::CompatibleBump
+    } else {
+        ChangeClass::Breaking
+    }
+}
+
+/// Score that blends how recent a version is with how heavily the ecosystem
+/// has adopted it, both normalized to `[0, 1]` against the rest of the
+/// candidate pool so neither axis dominates just because of its raw scale.
+fn adoption_score(
+    recency_rank: usize,
+    pool_size: usize,
+    downloads: u64,
+    max_downloads: u64,
+) -> f64 {
+    let recency = if pool_size > 1 {
+        1.0 - (recency_rank as f64 / (pool_size - 1) as f64)
+    } else {
+        1.0
+    };
+    let popularity = if max_downloads > 0 {
+        (downloads as f64 + 1.0).ln() / (max_downloads as f64 + 1.0).ln()
+    } else {
+        0.0
+    };
+    0.5 * recency + 0.5 * popularity
+}
+
+/// Tag each candidate version with its change class against whatever
+/// requirement the author already wrote, then order them to minimize
+/// disruption: versions still satisfying the current requirement first,
+/// then compatible bumps, then breaking changes. Within the satisfying and
+/// compatible tiers, candidates are further ordered by a weighted score of
+/// recency and crates.io download counts, so a heavily-used, well-tested
+/// version is tried before a barely-downloaded one of the same disruption
+/// class. Breaking changes are still ordered by proximity to the author's
+/// stated baseline first, since minimizing the size of a breaking jump
+/// matters more than its popularity.
+pub fn rank(
+    declaration: &Declaration,
+    crate_name: &str,
+    versions: Vec<VersionInfo>,
+) -> Result<Vec<Candidate>> {
+    let req = read_requirement(declaration, crate_name)?;
+
+    let mut parsed: Vec<(Version, VersionInfo)> = versions
+        .into_iter()
+        .filter_map(|info| Version::parse(&info.version).ok().map(|v| (v, info)))
+        .collect();
+    parsed.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let max_downloads = parsed
+        .iter()
+        .map(|(_, info)| info.downloads)
+        .max()
+        .unwrap_or(0);
+    let pool_size = parsed.len();
+
+    let mut satisfies = vec![];
+    let mut compatible = vec![];
+    let mut breaking = vec![];
+
+    for (rank, (version, info)) in parsed.into_iter().enumerate() {
+        let class = classify(req.as_ref(), &version);
+        let score = adoption_score(rank, pool_size, info.downloads, max_downloads);
+        let candidate = Candidate {
+            version: info.version,
+            class,
+            downloads: info.downloads,
+        };
+        match class {
+            ChangeClass::SatisfiesCurrent => satisfies.push((score, candidate)),
+            ChangeClass::CompatibleBump => compatible.push((score, candidate)),
+            ChangeClass::Breaking => breaking.push((version, candidate)),
+        }
+    }
+
+    satisfies.sort_by(|a, b| b.0.total_cmp(&a.0));
+    compatible.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    // Among breaking changes, the smallest jump away from the author's
+    // stated baseline is the least disruptive; downloads only break ties
+    // between equally-sized jumps.
+    if let Some(baseline) = req.as_ref().and_then(|r| r.comparators.first()) {
+        let baseline_major = baseline.major;
+        breaking.sort_by(|(a, ca), (b, cb)| {
+            a.major
+                .abs_diff(baseline_major)
+                .cmp(&b.major.abs_diff(baseline_major))
+                .then(cb.downloads.cmp(&ca.downloads))
+        });
+    }
+
+    let mut ranked: Vec<Candidate> = satisfies.into_iter().map(|(_, c)| c).collect();
+    ranked.extend(compatible.into_iter().map(|(_, c)| c));
+    ranked.extend(breaking.into_iter().map(|(_, c)| c));
+
+    Ok(ranked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn version(v: &str) -> Version {
+        Version::parse(v).unwrap()
+    }
+
+    #[test]
+    fn classify_matches_current_requirement() {
+        let req = VersionReq::parse("1.2").unwrap();
+        assert_eq!(
+            classify(Some(&req), &version("1.5.0")),
+            ChangeClass::SatisfiesCurrent
+        );
+    }
+
+    #[test]
+    fn classify_same_major_is_compatible_bump() {
+        let req = VersionReq::parse("1.2").unwrap();
+        // Below the stated minimum, but still within the same major line.
+        assert_eq!(
+            classify(Some(&req), &version("1.0.0")),
+            ChangeClass::CompatibleBump
+        );
+    }
+
+    #[test]
+    fn classify_different_major_is_breaking() {
+        let req = VersionReq::parse("1.2").unwrap();
+        assert_eq!(
+            classify(Some(&req), &version("2.0.0")),
+            ChangeClass::Breaking
+        );
+    }
+
+    #[test]
+    fn classify_zero_dot_x_treats_minor_as_the_compatible_boundary() {
+        // An exact requirement, so `req.matches(version)` fails for anything
+        // but "0.3.0" itself and classification actually reaches the
+        // hand-rolled 0.x-minor compatibility check below — a caret
+        // requirement like "0.3" already matches 0.3.9 outright and would
+        // never exercise that branch.
+        let req = VersionReq::parse("=0.3.0").unwrap();
+        assert_eq!(
+            classify(Some(&req), &version("0.3.9")),
+            ChangeClass::CompatibleBump
+        );
+        assert_eq!(
+            classify(Some(&req), &version("0.4.0")),
+            ChangeClass::Breaking
+        );
+    }
+
+    #[test]
+    fn classify_without_a_requirement_is_always_breaking() {
+        assert_eq!(classify(None, &version("1.0.0")), ChangeClass::Breaking);
+    }
+
+    #[test]
+    fn adoption_score_favors_recent_and_popular_versions() {
+        let newest_popular = adoption_score(0, 3, 1_000, 1_000);
+        let newest_unpopular = adoption_score(0, 3, 0, 1_000);
+        let oldest_popular = adoption_score(2, 3, 1_000, 1_000);
+        assert!(newest_popular > newest_unpopular);
+        assert!(newest_popular > oldest_popular);
+    }
+
+    fn temp_manifest(name: &str, contents: &str) -> Declaration {
+        let path = std::env::temp_dir().join(format!("winch-candidates-test-{name}.toml"));
+        fs::write(&path, contents).unwrap();
+        Declaration {
+            manifest_path: path,
+            table: vec!["dependencies".to_string()],
+        }
+    }
+
+    #[test]
+    fn rank_orders_satisfying_then_compatible_then_breaking() {
+        let declaration = temp_manifest("tiers", "[dependencies]\nserde = \"1.2\"\n");
+
+        let versions = vec![
+            VersionInfo {
+                version: "2.0.0".to_string(),
+                downloads: 0,
+            },
+            VersionInfo {
+                version: "1.0.0".to_string(),
+                downloads: 0,
+            },
+            VersionInfo {
+                version: "1.3.0".to_string(),
+                downloads: 0,
+            },
+        ];
+
+        let ranked = rank(&declaration, "serde", versions).unwrap();
+        let classes: Vec<ChangeClass> = ranked.iter().map(|c| c.class).collect();
+        assert_eq!(
+            classes,
+            vec![
+                ChangeClass::SatisfiesCurrent,
+                ChangeClass::CompatibleBump,
+                ChangeClass::Breaking,
+            ]
+        );
+
+        fs::remove_file(&declaration.manifest_path).ok();
+    }
+
+    #[test]
+    fn rank_lets_heavy_downloads_outweigh_recency_within_a_tier() {
+        let declaration = temp_manifest("downloads", "[dependencies]\nserde = \"1.0\"\n");
+
+        // All three satisfy "1.0"; the oldest is also the most heavily
+        // downloaded, and should be pulled ahead of the mid-aged,
+        // barely-downloaded version even though it isn't the newest.
+        let versions = vec![
+            VersionInfo {
+                version: "1.5.0".to_string(),
+                downloads: 0,
+            },
+            VersionInfo {
+                version: "1.3.0".to_string(),
+                downloads: 0,
+            },
+            VersionInfo {
+                version: "1.1.0".to_string(),
+                downloads: 1_000,
+            },
+        ];
+
+        let ranked = rank(&declaration, "serde", versions).unwrap();
+        let ordered_versions: Vec<&str> = ranked.iter().map(|c| c.version.as_str()).collect();
+        assert_eq!(ordered_versions, vec!["1.5.0", "1.1.0", "1.3.0"]);
+
+        fs::remove_file(&declaration.manifest_path).ok();
+    }
+
+    #[test]
+    fn rank_prefers_the_smallest_breaking_jump() {
+        let declaration = temp_manifest("breaking", "[dependencies]\nserde = \"1.0\"\n");
+
+        let versions = vec![
+            VersionInfo {
+                version: "4.0.0".to_string(),
+                downloads: 0,
+            },
+            VersionInfo {
+                version: "2.0.0".to_string(),
+                downloads: 0,
+            },
+        ];
+
+        let ranked = rank(&declaration, "serde", versions).unwrap();
+        assert_eq!(ranked[0].version, "2.0.0");
+        assert_eq!(ranked[1].version, "4.0.0");
+
+        fs::remove_file(&declaration.manifest_path).ok();
+    }
+
+    #[test]
+    fn rank_breaks_breaking_tier_ties_by_downloads() {
+        let declaration = temp_manifest("breaking-ties", "[dependencies]\nserde = \"1.0\"\n");
+
+        // Both are an equally-sized (one major version) jump away from the
+        // "1.0" baseline; the more heavily downloaded one should win the tie.
+        let versions = vec![
+            VersionInfo {
+                version: "2.0.0".to_string(),
+                downloads: 5,
+            },
+            VersionInfo {
+                version: "0.9.0".to_string(),
+                downloads: 500,
+            },
+        ];
+
+        let ranked = rank(&declaration, "serde", versions).unwrap();
+        assert_eq!(ranked[0].version, "0.9.0");
+        assert_eq!(ranked[1].version, "2.0.0");
+
+        fs::remove_file(&declaration.manifest_path).ok();
+    }
+}
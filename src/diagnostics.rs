@@ -0,0 +1,221 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// A crate that stood between the project and a successful build, together
+/// with whatever context we could recover about *why* it's a problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemCrate {
+    pub name: String,
+    /// The crate whose manifest pulled this one in, when known.
+    pub dependent: Option<String>,
+    /// The version requirement that couldn't be satisfied, when known.
+    pub requirement: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum CargoMessage {
+    CompilerMessage {
+        message: Diagnostic,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct Diagnostic {
+    code: Option<DiagnosticCode>,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+/// Re-run the build with `--message-format=json` and pull problem crates out
+/// of the structured diagnostics rather than grepping rustc/cargo's
+/// human-readable text, which changes wording across toolchain versions.
+pub fn collect_problem_crates(dir: &Path) -> Result<Vec<ProblemCrate>> {
+    collect_problem_crates_for_manifest(dir, None)
+}
+
+/// Same as [`collect_problem_crates`], but against a specific manifest (e.g.
+/// a `Cargo.winch.toml` overlay) rather than `dir`'s own `Cargo.toml`.
+pub fn collect_problem_crates_for_manifest(
+    dir: &Path,
+    manifest_path: Option<&Path>,
+) -> Result<Vec<ProblemCrate>> {
+    let mut problems = collect_missing_crates(dir, manifest_path)?;
+    problems.extend(collect_conflicting_crates(dir, manifest_path)?);
+    Ok(problems)
+}
+
+/// Find crates rustc couldn't locate (E0463) from `compiler-message` entries.
+///
+/// `--workspace` matters here: without it, `cargo build --manifest-path`
+/// only builds the one package the manifest belongs to, while the `cargo
+/// check --workspace` this is diagnosing builds every member. Omitting it
+/// would make a missing-crate error in a sibling member invisible to this
+/// rerun in exactly the multi-member workspaces this tool targets.
+fn collect_missing_crates(dir: &Path, manifest_path: Option<&Path>) -> Result<Vec<ProblemCrate>> {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["build", "--workspace", "--message-format=json"]);
+    if let Some(manifest_path) = manifest_path {
+        cmd.arg("--manifest-path").arg(manifest_path);
+    }
+    let output = cmd
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to re-run cargo build with --message-format=json")?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut missing = Vec::new();
+
+    for line in stdout.lines() {
+        let Ok(msg) = serde_json::from_str::<CargoMessage>(line) else {
+            continue;
+        };
+        let CargoMessage::CompilerMessage { message } = msg else {
+            continue;
+        };
+        let Some(code) = message.code else { continue };
+        if code.code != "E0463" {
+            continue;
+        }
+        if let Some(name) = extract_backticked_name(&message.message) {
+            missing.push(ProblemCrate {
+                name,
+                dependent: None,
+                requirement: None,
+            });
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Find crates cargo's resolver couldn't satisfy by asking `cargo metadata`
+/// to attempt the same package graph. Unlike rustc, cargo's resolver has no
+/// machine-readable error format, so this is the one place we still parse
+/// free text — but only cargo's own (relatively stable) resolver message,
+/// not version-specific rustc diagnostics.
+///
+/// As of cargo 1.95, a real unsatisfiable-requirement conflict reads:
+///
+/// ```text
+/// error: failed to select a version for the requirement `rand = "=99.0.0"`
+/// candidate versions found which didn't match: 0.10.2, 0.10.1, 0.10.0, ...
+/// location searched: `artifactory` index (which is replacing registry `crates-io`)
+/// required by package `a v0.1.0 (/path/to/a)`
+/// ```
+///
+/// Both the crate name and the failing requirement are on the first line;
+/// the dependent package name, when present at all, is on a later
+/// `required by package` line. There is no `require \`...\`` clause anywhere
+/// in the message — a regex built around one (as an earlier version of this
+/// function was) never matches real output.
+///
+/// Unlike `cargo build`/`check`, `cargo metadata` has no `--workspace` flag
+/// and doesn't need one: it always resolves and reports the whole
+/// workspace's dependency graph off the single shared `Cargo.lock`,
+/// regardless of which member's manifest `--manifest-path` points at, so a
+/// conflict in a sibling member is already visible here.
+fn collect_conflicting_crates(
+    dir: &Path,
+    manifest_path: Option<&Path>,
+) -> Result<Vec<ProblemCrate>> {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["metadata", "--format-version", "1"]);
+    if let Some(manifest_path) = manifest_path {
+        cmd.arg("--manifest-path").arg(manifest_path);
+    }
+    let output = cmd
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("failed to run cargo metadata")?;
+
+    if output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(parse_conflicting_crates(&stderr))
+}
+
+/// Pull `ProblemCrate`s out of `cargo metadata`'s resolver-conflict stderr.
+/// Split out from [`collect_conflicting_crates`] so the regex can be tested
+/// against a real captured message without shelling out to cargo.
+fn parse_conflicting_crates(stderr: &str) -> Vec<ProblemCrate> {
+    let requirement_re =
+        Regex::new(r#"failed to select a version for the requirement `([^ `]+) = "([^"]+)"`"#)
+            .unwrap();
+    let dependent_re = Regex::new(r"required by package `([^ `]+)").unwrap();
+
+    requirement_re
+        .captures_iter(stderr)
+        .map(|cap| {
+            let name = cap[1].to_string();
+            let requirement = cap[2].to_string();
+            let rest = &stderr[cap.get(0).unwrap().end()..];
+            let dependent = dependent_re
+                .captures(rest)
+                .map(|dep_cap| dep_cap[1].to_string());
+            ProblemCrate {
+                name,
+                dependent,
+                requirement: Some(requirement),
+            }
+        })
+        .collect()
+}
+
+fn extract_backticked_name(message: &str) -> Option<String> {
+    let start = message.find('`')? + 1;
+    let end = start + message[start..].find('`')?;
+    Some(message[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured verbatim from `cargo metadata` (cargo 1.95) against a
+    // two-member workspace where one member pins a nonexistent version of a
+    // crate the other member also depends on.
+    const REAL_CONFLICT_STDERR: &str = r#"    Updating `artifactory` index
+error: failed to select a version for the requirement `rand = "=99.0.0"`
+candidate versions found which didn't match: 0.10.2, 0.10.1, 0.10.0, ...
+location searched: `artifactory` index (which is replacing registry `crates-io`)
+required by package `a v0.1.0 (/tmp/conflict-test/a)`
+if you are looking for the prerelease package it needs to be specified explicitly
+    rand = { version = "0.10.0-rc.9" }
+perhaps a crate was updated and forgotten to be re-vendored?
+"#;
+
+    #[test]
+    fn parses_real_cargo_resolver_conflict_output() {
+        let problems = parse_conflicting_crates(REAL_CONFLICT_STDERR);
+        assert_eq!(
+            problems,
+            vec![ProblemCrate {
+                name: "rand".to_string(),
+                dependent: Some("a".to_string()),
+                requirement: Some("=99.0.0".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn returns_nothing_for_unrelated_stderr() {
+        assert!(parse_conflicting_crates("error: could not compile `a`").is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::workspace::Declaration;
+
+/// The full previous contents of one manifest, captured just before an
+/// applied change set overwrote it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestSnapshot {
+    manifest_path: PathBuf,
+    previous_contents: String,
+}
+
+/// One applied change set: when it happened, and enough to undo it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalEntry {
+    timestamp: u64,
+    snapshots: Vec<ManifestSnapshot>,
+}
+
+/// `.winch-history.json`'s on-disk shape: a simple append-only log of
+/// applied change sets, most recent last.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Journal {
+    entries: Vec<JournalEntry>,
+}
+
+fn journal_path(dir: &Path) -> PathBuf {
+    dir.join(".winch-history.json")
+}
+
+fn load(dir: &Path) -> Result<Journal> {
+    let path = journal_path(dir);
+    if !path.exists() {
+        return Ok(Journal::default());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn save(dir: &Path, journal: &Journal) -> Result<()> {
+    let path = journal_path(dir);
+    fs::write(&path, serde_json::to_string_pretty(journal)?)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Snapshot the current (pre-change) contents of every manifest that
+/// `assignment` is about to touch, and append them to the journal as a new
+/// entry. Call this *before* `workspace::apply_assignment`.
+pub fn record(
+    dir: &Path,
+    declarations: &BTreeMap<String, Declaration>,
+    assignment: &BTreeMap<String, String>,
+) -> Result<()> {
+    let mut manifests: Vec<&PathBuf> = assignment
+        .keys()
+        .filter_map(|name| declarations.get(name).map(|d| &d.manifest_path))
+        .collect();
+    manifests.sort();
+    manifests.dedup();
+
+    let mut snapshots = Vec::new();
+    for manifest_path in manifests {
+        let previous_contents = fs::read_to_string(manifest_path)
+            .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+        snapshots.push(ManifestSnapshot {
+            manifest_path: manifest_path.clone(),
+            previous_contents,
+        });
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let mut journal = load(dir)?;
+    journal.entries.push(JournalEntry {
+        timestamp,
+        snapshots,
+    });
+    save(dir, &journal)
+}
+
+/// Restore every manifest touched by the most recently applied change set
+/// to its pre-change contents, then drop that entry from the journal.
+pub fn revert(dir: &Path) -> Result<()> {
+    let mut journal = load(dir)?;
+    let Some(entry) = journal.entries.pop() else {
+        bail!("no applied change set to revert (.winch-history.json is empty)");
+    };
+
+    for snapshot in &entry.snapshots {
+        fs::write(&snapshot.manifest_path, &snapshot.previous_contents)
+            .with_context(|| format!("failed to restore {}", snapshot.manifest_path.display()))?;
+        println!("↩️  restored {}", snapshot.manifest_path.display());
+    }
+
+    save(dir, &journal)
+}
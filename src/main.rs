@@ -1,13 +1,16 @@
-cat ~/winch/src/main.rs
-use std::process::{Command, Stdio};
-use std::fs;
-use serde_json::Value;
-use toml_edit::{Document, value};
-use regex::Regex;
+use anyhow::Result;
 use reqwest::Client;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
-use anyhow::Result;
-use semver::Version;
+use std::process::{Command, Stdio};
+
+mod candidates;
+mod diagnostics;
+mod journal;
+mod resolver;
+mod workspace;
+
 const MAX_ROLLBACKS: usize = 5;
 
 #[tokio::main]
@@ -20,22 +23,31 @@ async fn main() -> Result<()> {
     }
 
     let dir = if let Some(pos) = args.iter().position(|a| a == "--dir") {
-        args.get(pos + 1).map(|s| PathBuf::from(s)).unwrap_or(std::env::current_dir()?)
+        args.get(pos + 1)
+            .map(|s| PathBuf::from(s))
+            .unwrap_or(std::env::current_dir()?)
     } else {
         std::env::current_dir()?
     };
 
+    if args.iter().any(|a| a == "--revert") {
+        journal::revert(&dir)?;
+        println!("✅ Reverted the last applied change set.");
+        return Ok(());
+    }
+
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let assume_yes = args.iter().any(|a| a == "--yes");
+
     println!("🛠️  Running Winch in directory: {}", dir.display());
 
     // --- Step 1: Initial build attempt ---
     let output = Command::new("cargo")
         .arg("build")
         .current_dir(&dir)
-        .stderr(Stdio::piped())
+        .stderr(Stdio::null())
         .output()?;
 
-    let stderr = String::from_utf8_lossy(&output.stderr);
-
     if output.status.success() {
         println!("✅ Cargo build succeeded! No dependency issues detected.");
         return Ok(());
@@ -43,158 +55,234 @@ async fn main() -> Result<()> {
 
     println!("⚠️  Build failed. Detecting dependency issues...");
 
-    // --- Step 2: Detect problems ---
-    let conflict_crates = parse_conflicts(&stderr);
-    let missing_crates = parse_missing_crates(&stderr);
-
-    let mut problem_crates = conflict_crates.clone();
-    problem_crates.extend(missing_crates.iter().cloned());
+    // --- Step 2: Detect problems from structured cargo/rustc diagnostics ---
+    let problems = diagnostics::collect_problem_crates(&dir)?;
 
-    if problem_crates.is_empty() {
+    if problems.is_empty() {
         println!("❌ No parseable dependency issues found.");
         return Ok(());
     }
 
-    println!("🧩 Problematic crates detected: {:?}", problem_crates);
+    println!("🧩 Problematic crates detected: {:?}", problems);
+
+    // --- Step 3: Locate which manifest/table declares each problem crate ---
+    let ws = workspace::discover(&dir)?;
+    let mut declarations = BTreeMap::new();
+    for problem in &problems {
+        let declaration = workspace::locate_or_default(
+            &ws,
+            &dir,
+            &problem.name,
+            problem.dependent.as_deref(),
+            problem.requirement.as_deref(),
+        )?;
+        declarations.insert(problem.name.clone(), declaration);
+    }
 
-    // --- Step 3: Fetch candidate versions ---
+    // --- Step 4: Fetch and rank candidate versions ---
     let client = Client::new();
-    let mut candidates_map = std::collections::HashMap::new();
-    for crate_name in &problem_crates {
-        let mut versions = get_candidate_versions(&client, crate_name).await?;
-
-        // For missing crates, ensure latest is first
-        if missing_crates.contains(crate_name) {
-            versions.sort_by(|a, b| Version::parse(b).unwrap().cmp(&Version::parse(a).unwrap()));
-
+    let mut candidates_map = BTreeMap::new();
+    let mut change_classes = std::collections::HashMap::new();
+    for problem in &problems {
+        let raw_versions = get_candidate_versions(&client, &problem.name).await?;
+        let declaration = &declarations[&problem.name];
+        let ranked = candidates::rank(declaration, &problem.name, raw_versions)?;
+
+        let mut versions = Vec::new();
+        for candidate in ranked.into_iter().take(MAX_ROLLBACKS) {
+            println!(
+                "   {} {} ({}, {} downloads)",
+                problem.name, candidate.version, candidate.class, candidate.downloads
+            );
+            change_classes.insert(
+                (problem.name.clone(), candidate.version.clone()),
+                candidate.class,
+            );
+            versions.push(candidate.version);
         }
-
-        candidates_map.insert(crate_name.clone(), versions);
+        candidates_map.insert(problem.name.clone(), versions);
     }
 
-    // --- Step 4: Generate version combinations ---
-    let all_combinations = generate_combinations(&candidates_map);
-    println!("🔄 Trying {} version combinations...", all_combinations.len());
+    // --- Step 5: Backtracking search over candidate versions ---
+    println!(
+        "🔎 Searching for a working combination (depth bound {})...",
+        MAX_ROLLBACKS
+    );
+    let outcome = resolver::resolve(&dir, &candidates_map, &declarations, MAX_ROLLBACKS)?;
 
-    // --- Step 5: Try combinations ---
-    for combo in all_combinations {
-        println!("🧪 Trying combination: {:?}", combo);
-
-        let cargo_toml_path = dir.join("Cargo.toml");
-        let cargo_toml_content = fs::read_to_string(&cargo_toml_path)?;
-        let mut doc = cargo_toml_content.parse::<Document>()?;
+    let Some(outcome) = outcome else {
+        println!("💀 All combinations failed. Manual intervention required.");
+        return Ok(());
+    };
 
-        for (crate_name, version) in &combo {
-            doc["dependencies"][crate_name] = value(version.clone());
-        }
+    println!("🎉 Found a working combination: {:?}", outcome.assignment);
+    for (crate_name, version) in &outcome.assignment {
+        let class = change_classes
+            .get(&(crate_name.clone(), version.clone()))
+            .copied()
+            .unwrap_or(candidates::ChangeClass::Breaking);
+        println!("   {} -> {} ({})", crate_name, version, class);
+    }
+    println!(
+        "📊 {} configuration(s) tried, {} cache hit(s), ~{:.1}s saved by the cache",
+        outcome.stats.configurations_tried,
+        outcome.stats.cache_hits,
+        outcome.stats.time_saved.as_secs_f64()
+    );
+
+    if dry_run {
+        print_plan(&declarations, &outcome.assignment, &change_classes)?;
+        println!("📝 Dry run: no manifests were written.");
+        return Ok(());
+    }
 
-        let winch_toml_path = dir.join("Cargo.winch.toml");
-        fs::write(&winch_toml_path, doc.to_string())?;
+    // The search only ran `cargo check`; confirm with a full workspace build
+    // before committing the change to the real manifests. Every distinct
+    // manifest the assignment touches needs its own build: passing only the
+    // first as `--manifest-path` would let cargo resolve any other touched
+    // member from its real, unpatched `Cargo.toml`.
+    let overlays =
+        workspace::write_overlays(&declarations, &outcome.assignment, "Cargo.winch.toml")?;
+    if overlays.is_empty() {
+        println!("❌ Nothing to apply.");
+        return Ok(());
+    }
 
+    let mut build_succeeded = true;
+    for manifest in &overlays {
         let build_result = Command::new("cargo")
-            .arg("build")
-            .arg("--manifest-path")
-            .arg(&winch_toml_path)
+            .args(["build", "--workspace", "--manifest-path"])
+            .arg(manifest)
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .current_dir(&dir)
             .spawn()?
             .wait()?;
 
-        if build_result.success() {
-            println!("🎉 Build succeeded with combination: {:?}", combo);
-            fs::write(&cargo_toml_path, doc.to_string())?;
-            println!("📦 Cargo.toml updated with working versions.");
+        if !build_result.success() {
+            build_succeeded = false;
+            break;
+        }
+    }
+
+    if !build_succeeded {
+        println!(
+            "💀 Full build failed despite a passing cargo check. Manual intervention required."
+        );
+        return Ok(());
+    }
+
+    if !assume_yes {
+        print_plan(&declarations, &outcome.assignment, &change_classes)?;
+        if !confirm("Write these changes to Cargo.toml?")? {
+            println!("❌ Aborted; manifests left unchanged.");
             return Ok(());
-        } else {
-            println!("❌ Build failed. Trying next combination...");
         }
     }
 
-    println!("💀 All combinations failed. Manual intervention required.");
+    journal::record(&dir, &declarations, &outcome.assignment)?;
+    workspace::apply_assignment(&declarations, &outcome.assignment)?;
+    println!("📦 Manifests updated with working versions.");
+    println!("   (run with --revert to undo this change set)");
     Ok(())
 }
 
+/// Print the proposed requirement -> version changes, e.g. for a `--dry-run`
+/// or as the confirmation prompt's preview.
+fn print_plan(
+    declarations: &BTreeMap<String, workspace::Declaration>,
+    assignment: &resolver::Assignment,
+    change_classes: &std::collections::HashMap<(String, String), candidates::ChangeClass>,
+) -> Result<()> {
+    println!("📝 Proposed changes:");
+    for (crate_name, version) in assignment {
+        let old_requirement = declarations
+            .get(crate_name)
+            .map(|declaration| workspace::read_requirement_string(declaration, crate_name))
+            .transpose()?
+            .flatten()
+            .unwrap_or_else(|| "(none)".to_string());
+        let class = change_classes
+            .get(&(crate_name.clone(), version.clone()))
+            .copied()
+            .unwrap_or(candidates::ChangeClass::Breaking);
+        println!("   {crate_name}: {old_requirement} -> {version} ({class})");
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on stdin, defaulting to "no" on anything else
+/// (including EOF, so non-interactive runs fail closed rather than applying
+/// unattended changes).
+fn confirm(prompt: &str) -> Result<bool> {
+    use std::io::Write;
+    print!("{prompt} [y/N] ");
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 /// Print help message
 fn print_help() {
     println!("🛠️  Winch — Automatic Cargo Dependency Resolver");
     println!();
     println!("Usage:");
-    println!("  winch [--dir <path>] [--help|-h]");
+    println!("  winch [--dir <path>] [--dry-run] [--yes] [--revert] [--help|-h]");
     println!();
     println!("Options:");
     println!("  --dir <path>   Path to Rust project (default: current dir)");
+    println!("  --dry-run      Print the proposed version changes and exit without writing");
+    println!("  --yes          Apply the resolved changes without an interactive prompt");
+    println!("  --revert       Restore Cargo.toml from the last applied change set");
     println!("  --help, -h     Show this help message");
 }
 
-/// Parse cargo stderr for conflicting crates
-fn parse_conflicts(stderr: &str) -> Vec<String> {
-    let re = Regex::new(r#"failed to select a version for `([^`]*)`"#).unwrap();
-    re.captures_iter(stderr)
-        .map(|cap| cap[1].to_string())
-        .collect()
-}
+/// Fetch candidate versions from crates.io, along with each version's
+/// download count so `candidates::rank` can weigh adoption alongside
+/// recency and semver compatibility. Returns an empty list rather than
+/// erroring out the whole run when crates.io can't answer for this crate
+/// (404 for a nonexistent/typo'd name, rate limiting, a transient error
+/// page) — a very plausible cause for exactly the missing-crate problems
+/// this is called for.
+async fn get_candidate_versions(
+    client: &Client,
+    crate_name: &str,
+) -> Result<Vec<candidates::VersionInfo>> {
+    let url = format!("https://crates.io/api/v1/crates/{}", crate_name);
+    let response = client.get(&url).send().await?;
+
+    if !response.status().is_success() {
+        eprintln!(
+            "⚠️  crates.io returned {} for `{}`; skipping",
+            response.status(),
+            crate_name
+        );
+        return Ok(Vec::new());
+    }
 
-/// Parse cargo stderr for missing crates
-fn parse_missing_crates(stderr: &str) -> Vec<String> {
-    let mut missing = vec![];
-    let re1 = Regex::new(r#"can't find crate for `([^`]*)`"#).unwrap();
-    missing.extend(re1.captures_iter(stderr).map(|cap| cap[1].to_string()));
-    let re2 = Regex::new(r#"could not find `([^`]*)` in registry"#).unwrap();
-    missing.extend(re2.captures_iter(stderr).map(|cap| cap[1].to_string()));
-    missing
-}
+    let resp: Value = response.json().await?;
 
-/// Fetch top candidate versions from crates.io
-async fn get_candidate_versions(client: &Client, crate_name: &str) -> Result<Vec<String>> {
-    let url = format!("https://crates.io/api/v1/crates/{}", crate_name);
-    let resp: Value = client.get(&url).send().await?.json().await?;
+    let Some(raw_versions) = resp["versions"].as_array() else {
+        eprintln!("⚠️  unexpected crates.io response shape for `{crate_name}`; skipping");
+        return Ok(Vec::new());
+    };
 
-    let versions = resp["versions"]
-        .as_array()
-        .unwrap()
+    let versions = raw_versions
         .iter()
         .filter_map(|v| {
             let vers = v["num"].as_str()?;
             let yanked = v["yanked"].as_bool().unwrap_or(false);
-            if !yanked { Some(vers.to_string()) } else { None }
+            if yanked {
+                return None;
+            }
+            let downloads = v["downloads"].as_u64().unwrap_or(0);
+            Some(candidates::VersionInfo {
+                version: vers.to_string(),
+                downloads,
+            })
         })
-        .take(MAX_ROLLBACKS)
-        .collect::<Vec<String>>();
+        .collect::<Vec<candidates::VersionInfo>>();
 
     Ok(versions)
 }
-
-/// Generate all combinations of candidate versions
-fn generate_combinations(
-    candidates_map: &std::collections::HashMap<String, Vec<String>>
-) -> Vec<std::collections::HashMap<String, String>> {
-    let keys: Vec<&String> = candidates_map.keys().collect();
-    let mut lists: Vec<&Vec<String>> = vec![];
-    for k in &keys { lists.push(candidates_map.get(*k).unwrap()); }
-
-    let mut combos = vec![];
-    let mut indices = vec![0; lists.len()];
-
-    loop {
-        let mut combo = std::collections::HashMap::new();
-        for (i, &key) in keys.iter().enumerate() {
-            combo.insert(key.clone(), lists[i][indices[i]].clone());
-        }
-        combos.push(combo);
-
-        let mut carry = 1;
-        for i in 0..indices.len() {
-            if carry == 0 { break; }
-            indices[i] += carry;
-            if indices[i] >= lists[i].len() {
-                indices[i] = 0;
-            } else {
-                carry = 0;
-            }
-        }
-        if carry == 1 { break; }
-    }
-
-    combos
-}
@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rayon::prelude::*;
+
+use crate::diagnostics;
+use crate::workspace::{self, Declaration};
+
+/// A crate-name -> version pin. Ordered so it can be hashed/compared for
+/// memoization regardless of insertion order.
+pub type Assignment = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, Default)]
+pub struct ResolveStats {
+    pub configurations_tried: usize,
+    pub cache_hits: usize,
+    pub time_saved: Duration,
+}
+
+pub struct ResolveOutcome {
+    pub assignment: Assignment,
+    pub stats: ResolveStats,
+}
+
+#[derive(Clone)]
+struct CacheEntry {
+    unsatisfied: HashSet<String>,
+    duration: Duration,
+}
+
+struct SharedState {
+    cache: Mutex<HashMap<Assignment, CacheEntry>>,
+    stats: Mutex<ResolveStats>,
+    checks_run: AtomicUsize,
+}
+
+impl SharedState {
+    fn record_check(&self) {
+        self.checks_run.fetch_add(1, Ordering::Relaxed);
+        let mut stats = self.stats.lock().unwrap();
+        stats.configurations_tried += 1;
+    }
+
+    fn record_cache_hit(&self, duration: Duration) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.cache_hits += 1;
+        stats.time_saved += duration;
+    }
+}
+
+/// Depth-first backtracking search over per-crate candidate versions,
+/// parallelized with rayon: at each decision point every still-untried
+/// candidate for the current crate is evaluated concurrently in its own
+/// `CARGO_TARGET_DIR`, so independent branches don't serialize behind one
+/// another. A shared, `Mutex`-guarded cache keyed on the sorted
+/// crate->version assignment ensures the same partial configuration is
+/// never rebuilt twice, even when two branches arrive at it from different
+/// orders. `MAX_ROLLBACKS` (passed in as `max_depth`) bounds the recursion
+/// depth, and caps the total number of `cargo check` invocations at
+/// `max_depth` times the total number of candidates.
+pub fn resolve(
+    dir: &Path,
+    candidates_map: &BTreeMap<String, Vec<String>>,
+    declarations: &BTreeMap<String, Declaration>,
+    max_depth: usize,
+) -> Result<Option<ResolveOutcome>> {
+    resolve_with(candidates_map, max_depth, |trial| {
+        check_combination(dir, trial, declarations)
+    })
+}
+
+/// The actual backtracking search, parameterized over how a trial
+/// assignment is evaluated. Split out from [`resolve`] so the search and
+/// caching logic can be exercised with a fake, in-memory `checker` in
+/// tests, without needing a real cargo project on disk.
+fn resolve_with<F>(
+    candidates_map: &BTreeMap<String, Vec<String>>,
+    max_depth: usize,
+    checker: F,
+) -> Result<Option<ResolveOutcome>>
+where
+    F: Fn(&Assignment) -> Result<HashSet<String>> + Sync,
+{
+    let remaining: Vec<String> = candidates_map.keys().cloned().collect();
+    let max_checks = candidates_map
+        .values()
+        .map(|v| v.len())
+        .sum::<usize>()
+        .max(1)
+        * max_depth;
+
+    let shared = SharedState {
+        cache: Mutex::new(HashMap::new()),
+        stats: Mutex::new(ResolveStats::default()),
+        checks_run: AtomicUsize::new(0),
+    };
+
+    let assignment = backtrack(
+        candidates_map,
+        &remaining,
+        Assignment::new(),
+        &shared,
+        max_depth,
+        max_checks,
+        &checker,
+    );
+
+    let stats = shared.stats.into_inner().unwrap();
+    Ok(assignment.map(|assignment| ResolveOutcome { assignment, stats }))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn backtrack<F>(
+    candidates_map: &BTreeMap<String, Vec<String>>,
+    remaining: &[String],
+    assignment: Assignment,
+    shared: &SharedState,
+    max_depth: usize,
+    max_checks: usize,
+    checker: &F,
+) -> Option<Assignment>
+where
+    F: Fn(&Assignment) -> Result<HashSet<String>> + Sync,
+{
+    if remaining.is_empty() {
+        return Some(assignment);
+    }
+    if assignment.len() >= max_depth || shared.checks_run.load(Ordering::Relaxed) >= max_checks {
+        return None;
+    }
+
+    let crate_name = &remaining[0];
+    let candidates = candidates_map.get(crate_name)?;
+
+    // `find_map_first`, not `find_map_any`: candidates are pre-ranked
+    // (`candidates::rank`) so the first one that works is also the most
+    // preferable one. `find_map_any` would return whichever branch's `cargo
+    // check` happened to finish first, silently discarding that ordering.
+    candidates.par_iter().find_map_first(|version| {
+        let mut trial = assignment.clone();
+        trial.insert(crate_name.clone(), version.clone());
+
+        let still_unsatisfied = evaluate(&trial, shared, checker);
+        if still_unsatisfied.contains(crate_name) {
+            return None;
+        }
+
+        let mut next_remaining: Vec<String> = remaining[1..].to_vec();
+        for name in &still_unsatisfied {
+            if candidates_map.contains_key(name) && !next_remaining.contains(name) {
+                next_remaining.push(name.clone());
+            }
+        }
+
+        backtrack(
+            candidates_map,
+            &next_remaining,
+            trial,
+            shared,
+            max_depth,
+            max_checks,
+            checker,
+        )
+    })
+}
+
+/// Evaluate `trial` with `checker`, consulting (and populating) the shared
+/// cache first.
+fn evaluate<F>(trial: &Assignment, shared: &SharedState, checker: &F) -> HashSet<String>
+where
+    F: Fn(&Assignment) -> Result<HashSet<String>> + Sync,
+{
+    if let Some(entry) = shared.cache.lock().unwrap().get(trial).cloned() {
+        shared.record_cache_hit(entry.duration);
+        return entry.unsatisfied;
+    }
+
+    let started = Instant::now();
+    let unsatisfied = checker(trial).unwrap_or_else(|err| {
+        eprintln!("⚠️  check failed to run for {:?}: {err}", trial);
+        trial.keys().cloned().collect()
+    });
+    let duration = started.elapsed();
+    shared.record_check();
+
+    shared.cache.lock().unwrap().insert(
+        trial.clone(),
+        CacheEntry {
+            unsatisfied: unsatisfied.clone(),
+            duration,
+        },
+    );
+
+    unsatisfied
+}
+
+/// Stage `assignment` as per-worker `Cargo.winch.toml` overlays (so
+/// concurrent branches never clobber each other's overlay files) and run
+/// `cargo check --workspace` against *every* distinct manifest the
+/// assignment touches, not just the first. A single `--manifest-path` only
+/// makes cargo resolve workspace members via their own real, unmodified
+/// `Cargo.toml` — the overlay sitting next to a non-entry member is never
+/// referenced by cargo at all. So when a trial patches crates declared in
+/// two different member manifests, each member's overlay has to be checked
+/// as its own entry point for that member's patch to actually be exercised.
+/// Returns the union of crates any of those checks still blame.
+fn check_combination(
+    dir: &Path,
+    trial: &Assignment,
+    declarations: &BTreeMap<String, Declaration>,
+) -> Result<HashSet<String>> {
+    let worker = rayon::current_thread_index().unwrap_or(0);
+    let overlay_name = format!("Cargo.winch.worker-{worker}.toml");
+
+    let overlays = workspace::write_overlays(declarations, trial, &overlay_name)?;
+    if overlays.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let target_dir = worker_target_dir(dir, worker);
+    std::fs::create_dir_all(&target_dir)?;
+
+    let mut unsatisfied = HashSet::new();
+    for manifest in &overlays {
+        let output = Command::new("cargo")
+            .args(["check", "--workspace", "--manifest-path"])
+            .arg(manifest)
+            .env("CARGO_TARGET_DIR", &target_dir)
+            .current_dir(dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+
+        if output.status.success() {
+            continue;
+        }
+
+        let problems = diagnostics::collect_problem_crates_for_manifest(dir, Some(manifest))?;
+        if problems.is_empty() {
+            // diagnostics only recognizes E0463 and resolver conflicts, but
+            // this check still failed -- most likely an ordinary
+            // API-incompatible-bump compile error (E0308/E0599/a broken
+            // trait bound) that diagnostics doesn't attribute to any crate.
+            // Treating an unrecognized failure as a pass would let
+            // backtracking lock in the first candidate for every crate this
+            // manifest touches without ever trying the rest of the ranked
+            // list, so mark all of them suspect instead.
+            let real_manifest = manifest.with_file_name("Cargo.toml");
+            unsatisfied.extend(
+                trial
+                    .keys()
+                    .filter(|name| {
+                        declarations
+                            .get(*name)
+                            .is_some_and(|d| d.manifest_path == real_manifest)
+                    })
+                    .cloned(),
+            );
+        } else {
+            unsatisfied.extend(problems.into_iter().map(|p| p.name));
+        }
+    }
+
+    Ok(unsatisfied)
+}
+
+fn worker_target_dir(dir: &Path, worker: usize) -> PathBuf {
+    dir.join(".winch-target").join(format!("worker-{worker}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize as CallCounter;
+
+    fn candidates(pairs: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        pairs
+            .iter()
+            .map(|(name, versions)| {
+                (
+                    name.to_string(),
+                    versions.iter().map(|v| v.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolve_with_finds_a_combined_assignment_across_two_crates() {
+        // Each crate only checks out at its second candidate, "2.0", so the
+        // search has to move past "1.0" for `a` and then for `b` and combine
+        // both pins into one assignment.
+        let map = candidates(&[("a", &["1.0", "2.0"]), ("b", &["1.0", "2.0"])]);
+
+        let outcome = resolve_with(&map, 5, |trial| {
+            let mut unsatisfied = HashSet::new();
+            if trial.get("a").map(String::as_str) != Some("2.0") {
+                unsatisfied.insert("a".to_string());
+            }
+            if trial.get("b").map(String::as_str) != Some("2.0") {
+                unsatisfied.insert("b".to_string());
+            }
+            Ok(unsatisfied)
+        })
+        .unwrap();
+
+        let outcome = outcome.expect("a satisfying assignment exists");
+        assert_eq!(outcome.assignment.get("a").map(String::as_str), Some("2.0"));
+        assert_eq!(outcome.assignment.get("b").map(String::as_str), Some("2.0"));
+    }
+
+    #[test]
+    fn resolve_with_prefers_the_first_candidate_when_several_would_work() {
+        // All three candidates for `a` pass the checker, but they're listed
+        // in preference order -- the search must commit to the first one,
+        // not whichever candidate's (simulated) check happens to finish
+        // first.
+        let map = candidates(&[("a", &["1.0", "2.0", "3.0"])]);
+
+        let outcome = resolve_with(&map, 5, |_trial| Ok(HashSet::new()))
+            .unwrap()
+            .expect("every candidate satisfies");
+
+        assert_eq!(outcome.assignment.get("a").map(String::as_str), Some("1.0"));
+    }
+
+    #[test]
+    fn resolve_with_reports_none_when_unsatisfiable() {
+        let map = candidates(&[("a", &["1.0", "2.0"])]);
+
+        let outcome = resolve_with(&map, 5, |_trial| {
+            // No candidate for `a` is ever acceptable.
+            Ok(HashSet::from(["a".to_string()]))
+        })
+        .unwrap();
+
+        assert!(outcome.is_none());
+    }
+
+    #[test]
+    fn evaluate_caches_repeated_trial_assignments() {
+        // Same trial assignment checked twice through `evaluate` should only
+        // ever invoke the checker once; the second call is served from cache.
+        let shared = SharedState {
+            cache: Mutex::new(HashMap::new()),
+            stats: Mutex::new(ResolveStats::default()),
+            checks_run: AtomicUsize::new(0),
+        };
+        let calls = CallCounter::new(0);
+        let checker = |_trial: &Assignment| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Ok(HashSet::new())
+        };
+
+        let mut trial = Assignment::new();
+        trial.insert("a".to_string(), "1.0".to_string());
+
+        evaluate(&trial, &shared, &checker);
+        evaluate(&trial, &shared, &checker);
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(shared.stats.into_inner().unwrap().cache_hits, 1);
+    }
+}
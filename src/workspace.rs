@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use toml_edit::{value, Document, Item};
+
+/// A workspace member crate as reported by `cargo metadata`. A plain,
+/// non-workspace project is modeled as a workspace of exactly one member.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub manifest_path: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root_manifest_path: PathBuf,
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataDoc {
+    packages: Vec<MetaPackage>,
+    workspace_members: Vec<String>,
+    workspace_root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetaPackage {
+    id: String,
+    name: String,
+    manifest_path: PathBuf,
+}
+
+/// Enumerate every workspace member via `cargo metadata`, so later steps can
+/// patch whichever manifest actually declares a given crate instead of
+/// assuming a single `Cargo.toml` with a `[dependencies]` table.
+pub fn discover(dir: &Path) -> Result<Workspace> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to run cargo metadata")?;
+
+    let doc: MetadataDoc =
+        serde_json::from_slice(&output.stdout).context("failed to parse cargo metadata output")?;
+
+    let members = doc
+        .packages
+        .into_iter()
+        .filter(|pkg| doc.workspace_members.contains(&pkg.id))
+        .map(|pkg| Member {
+            name: pkg.name,
+            manifest_path: pkg.manifest_path,
+        })
+        .collect();
+
+    Ok(Workspace {
+        root_manifest_path: doc.workspace_root.join("Cargo.toml"),
+        members,
+    })
+}
+
+/// Where a crate's version requirement actually lives: a specific dependency
+/// table in a member's manifest, or the workspace-level
+/// `[workspace.dependencies]` table when the member only inherits it via
+/// `crate = { workspace = true }`.
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub manifest_path: PathBuf,
+    pub table: Vec<String>,
+}
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Find whichever manifest and table declares `crate_name`, searching every
+/// member's `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]`
+/// before falling back to the workspace root's `[workspace.dependencies]`.
+///
+/// Multiple members can declare the same crate name with different
+/// requirements, so a bare name search can pick the wrong one. `dependent`
+/// (the package diagnostics actually blamed) and `requirement` (the version
+/// requirement that failed to resolve) disambiguate between candidates when
+/// given: a member whose name matches `dependent` wins outright, otherwise a
+/// member whose declared requirement string matches `requirement` wins.
+/// Without either hint, this falls back to the first member found, same as
+/// before.
+pub fn locate(
+    workspace: &Workspace,
+    crate_name: &str,
+    dependent: Option<&str>,
+    requirement: Option<&str>,
+) -> Result<Option<Declaration>> {
+    let mut matches: Vec<(&str, Declaration)> = Vec::new();
+
+    for member in &workspace.members {
+        let content = fs::read_to_string(&member.manifest_path)
+            .with_context(|| format!("failed to read {}", member.manifest_path.display()))?;
+        let doc = content.parse::<Document>()?;
+
+        for table in DEPENDENCY_TABLES {
+            let Some(item) = doc[table].get(crate_name) else {
+                continue;
+            };
+
+            let inherits_workspace = item
+                .as_table_like()
+                .and_then(|t| t.get("workspace"))
+                .and_then(Item::as_bool)
+                .unwrap_or(false);
+
+            let declaration = if inherits_workspace {
+                Declaration {
+                    manifest_path: workspace.root_manifest_path.clone(),
+                    table: vec!["workspace".to_string(), "dependencies".to_string()],
+                }
+            } else {
+                Declaration {
+                    manifest_path: member.manifest_path.clone(),
+                    table: vec![table.to_string()],
+                }
+            };
+            matches.push((member.name.as_str(), declaration));
+            break;
+        }
+    }
+
+    if let Some(dependent) = dependent {
+        if let Some((_, declaration)) = matches.iter().find(|(name, _)| *name == dependent) {
+            return Ok(Some(declaration.clone()));
+        }
+    }
+
+    if let Some(requirement) = requirement {
+        for (_, declaration) in &matches {
+            if read_requirement_string(declaration, crate_name)?.as_deref() == Some(requirement) {
+                return Ok(Some(declaration.clone()));
+            }
+        }
+    }
+
+    if let Some((_, declaration)) = matches.into_iter().next() {
+        return Ok(Some(declaration));
+    }
+
+    let root_content = fs::read_to_string(&workspace.root_manifest_path)
+        .with_context(|| format!("failed to read {}", workspace.root_manifest_path.display()))?;
+    let root_doc = root_content.parse::<Document>()?;
+    if root_doc["workspace"]["dependencies"]
+        .get(crate_name)
+        .is_some()
+    {
+        return Ok(Some(Declaration {
+            manifest_path: workspace.root_manifest_path.clone(),
+            table: vec!["workspace".to_string(), "dependencies".to_string()],
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Locate `crate_name`'s declaration, or default to the `[dependencies]`
+/// table of whichever member lives at `dir` (falling back to the workspace
+/// root) for crates that aren't declared anywhere yet, e.g. ones rustc
+/// reports missing (E0463) because no manifest references them at all.
+/// `dependent`/`requirement` are forwarded to [`locate`] to disambiguate
+/// between members that declare the same crate name, and `dependent` is
+/// also used to pick the right member manifest in the not-declared-anywhere
+/// default case.
+pub fn locate_or_default(
+    workspace: &Workspace,
+    dir: &Path,
+    crate_name: &str,
+    dependent: Option<&str>,
+    requirement: Option<&str>,
+) -> Result<Declaration> {
+    if let Some(declaration) = locate(workspace, crate_name, dependent, requirement)? {
+        return Ok(declaration);
+    }
+
+    let manifest_path = dependent
+        .and_then(|dependent| workspace.members.iter().find(|m| m.name == dependent))
+        .map(|member| member.manifest_path.clone())
+        .or_else(|| {
+            workspace
+                .members
+                .iter()
+                .find(|member| member.manifest_path.parent() == Some(dir))
+                .map(|member| member.manifest_path.clone())
+        })
+        .unwrap_or_else(|| workspace.root_manifest_path.clone());
+
+    Ok(Declaration {
+        manifest_path,
+        table: vec!["dependencies".to_string()],
+    })
+}
+
+fn get_dependency_item<'a>(
+    doc: &'a Document,
+    table: &[String],
+    crate_name: &str,
+) -> Option<&'a Item> {
+    match table {
+        [t1] => doc[t1.as_str()].get(crate_name),
+        [t1, t2] => doc[t1.as_str()][t2.as_str()].get(crate_name),
+        _ => None,
+    }
+}
+
+/// Read the raw version requirement string for `crate_name` out of
+/// `declaration`'s manifest and table, if it's declared as a plain version
+/// (`"1.2"`) or a table with a `version` key (`{ version = "1.2" }`).
+pub fn read_requirement_string(
+    declaration: &Declaration,
+    crate_name: &str,
+) -> Result<Option<String>> {
+    let content = fs::read_to_string(&declaration.manifest_path)
+        .with_context(|| format!("failed to read {}", declaration.manifest_path.display()))?;
+    let doc = content.parse::<Document>()?;
+
+    let Some(item) = get_dependency_item(&doc, &declaration.table, crate_name) else {
+        return Ok(None);
+    };
+
+    Ok(match item {
+        Item::Value(v) => v
+            .as_str()
+            .or_else(|| {
+                v.as_inline_table()
+                    .and_then(|t| t.get("version"))
+                    .and_then(|version| version.as_str())
+            })
+            .map(str::to_string),
+        Item::Table(t) => t.get("version").and_then(Item::as_str).map(str::to_string),
+        _ => None,
+    })
+}
+
+fn set_dependency_version(doc: &mut Document, table: &[String], crate_name: &str, version: &str) {
+    match table {
+        [t1] => doc[t1.as_str()][crate_name] = value(version),
+        [t1, t2] => doc[t1.as_str()][t2.as_str()][crate_name] = value(version),
+        _ => unreachable!("dependency tables are at most two levels deep"),
+    }
+}
+
+type ManifestPatches<'a> = BTreeMap<PathBuf, Vec<(&'a [String], &'a str, &'a str)>>;
+
+fn group_by_manifest<'a>(
+    declarations: &'a BTreeMap<String, Declaration>,
+    assignment: &'a BTreeMap<String, String>,
+) -> ManifestPatches<'a> {
+    let mut by_manifest: ManifestPatches<'a> = BTreeMap::new();
+    for (crate_name, version) in assignment {
+        let Some(declaration) = declarations.get(crate_name) else {
+            continue;
+        };
+        by_manifest
+            .entry(declaration.manifest_path.clone())
+            .or_default()
+            .push((
+                declaration.table.as_slice(),
+                crate_name.as_str(),
+                version.as_str(),
+            ));
+    }
+    by_manifest
+}
+
+/// Write `assignment` into an `overlay_file_name` overlay (e.g.
+/// `Cargo.winch.toml`, or a per-worker variant for concurrent trials) next
+/// to each manifest it touches, leaving the real files untouched. Returns
+/// the overlay paths with the workspace-root overlay (if any) listed first,
+/// so callers can prefer it as the `cargo check --manifest-path` entry
+/// point when a `[workspace.dependencies]` bump is in play.
+pub fn write_overlays(
+    declarations: &BTreeMap<String, Declaration>,
+    assignment: &BTreeMap<String, String>,
+    overlay_file_name: &str,
+) -> Result<Vec<PathBuf>> {
+    let by_manifest = group_by_manifest(declarations, assignment);
+    let mut member_overlays = Vec::new();
+    let mut root_overlay = None;
+
+    for (manifest_path, patches) in by_manifest {
+        let content = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+        let mut doc = content.parse::<Document>()?;
+        let is_root = patches
+            .iter()
+            .any(|(table, _, _)| table.first().map(String::as_str) == Some("workspace"));
+
+        for (table, crate_name, version) in patches {
+            set_dependency_version(&mut doc, table, crate_name, version);
+        }
+
+        let overlay_path = manifest_path.with_file_name(overlay_file_name);
+        fs::write(&overlay_path, doc.to_string())?;
+
+        if is_root {
+            root_overlay = Some(overlay_path);
+        } else {
+            member_overlays.push(overlay_path);
+        }
+    }
+
+    let mut overlays = root_overlay.into_iter().collect::<Vec<_>>();
+    overlays.extend(member_overlays);
+    Ok(overlays)
+}
+
+/// Write `assignment` back into the real manifests it was resolved against.
+pub fn apply_assignment(
+    declarations: &BTreeMap<String, Declaration>,
+    assignment: &BTreeMap<String, String>,
+) -> Result<()> {
+    let by_manifest = group_by_manifest(declarations, assignment);
+    for (manifest_path, patches) in by_manifest {
+        let content = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+        let mut doc = content.parse::<Document>()?;
+        for (table, crate_name, version) in patches {
+            set_dependency_version(&mut doc, table, crate_name, version);
+        }
+        fs::write(&manifest_path, doc.to_string())?;
+    }
+    Ok(())
+}